@@ -4,37 +4,143 @@ use axum::{
     body::StreamBody,
     debug_handler,
     extract::{Path, State},
-    headers::{ETag, IfNoneMatch},
+    headers::{ETag, IfNoneMatch, Range},
     http::header,
     http::StatusCode,
-    response::{Html, IntoResponse, Redirect, Response},
-    routing::{delete, get},
+    response::sse::{Event, KeepAlive, Sse},
+    response::{Html, IntoResponse, Json, Redirect, Response},
+    routing::{delete, get, post},
     Router,
     TypedHeader,
 };
-use clap::Parser;
+use async_trait::async_trait;
+use axum::extract::Query;
+use clap::{Parser, Subcommand};
+use image::GenericImageView;
 use regex::Regex;
 use log::{debug, info, error};
+use serde::{Deserialize, Serialize};
 use sha256;
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::sync::Arc;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Arc, RwLock};
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
 use tokio_util::io::ReaderStream;
 use tower_http::services::ServeDir;
+use walkdir::WalkDir;
+
+/// Bounding-box size used for thumbnails when no `max_dim` is supplied.
+const DEFAULT_THUMB_MAX_DIM: u32 = 400;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    // The file containing the output from photodedupe to process
-    filename: String,
+    #[command(subcommand)]
+    command: Command,
+
+    /// Serve the duplicate set from a remote host over SFTP (`host:port`)
+    /// instead of the local filesystem.
+    #[arg(long, value_name = "HOST:PORT")]
+    sftp: Option<String>,
+
+    /// SFTP username (agent authentication), used with `--sftp`.
+    #[arg(long, default_value = "root")]
+    user: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Review the text output of a photodedupe run.
+    Parse {
+        // The file containing the output from photodedupe to process
+        filename: String,
+    },
+    /// Scan a directory for near-duplicate images with a built-in perceptual hash.
+    Scan {
+        // The directory to walk for images
+        dir: String,
+        /// Maximum Hamming distance between fingerprints to treat as duplicates.
+        #[arg(long, default_value_t = 10)]
+        threshold: u32,
+    },
+}
+
+/// Disjoint-set forest used to coalesce transitively-similar images into groups.
+struct UnionFind {
+    parent: Vec<usize>,
+    rank: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        Self {
+            parent: (0..size).collect(),
+            rank: vec![0; size],
+        }
+    }
+
+    fn find(&mut self, x: usize) -> usize {
+        if self.parent[x] != x {
+            let root = self.find(self.parent[x]);
+            self.parent[x] = root;
+        }
+        self.parent[x]
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra == rb {
+            return;
+        }
+        if self.rank[ra] < self.rank[rb] {
+            self.parent[ra] = rb;
+        } else if self.rank[ra] > self.rank[rb] {
+            self.parent[rb] = ra;
+        } else {
+            self.parent[rb] = ra;
+            self.rank[ra] += 1;
+        }
+    }
 }
 
-/// The path and size of a single - potentially duplicate - image.
+/// Whether a duplicate entry is a still image or a video clip.
+#[derive(Clone, Copy, PartialEq)]
+enum MediaKind {
+    Image,
+    Video,
+}
+
+/// The path and size of a single - potentially duplicate - media file.
 #[derive(Clone)]
 struct ImgInfo {
     path: String,
     width: u32,
     height: u32,
+    kind: MediaKind,
+}
+
+impl ImgInfo {
+    /// Whether this entry is a video clip (used by the group template).
+    fn is_video(&self) -> bool {
+        self.kind == MediaKind::Video
+    }
+}
+
+/// Guess whether `path` points at a video based on its MIME type.
+fn media_kind(path: &str) -> MediaKind {
+    let is_video = mime_guess::from_path(path)
+        .first()
+        .map(|m| m.type_() == mime_guess::mime::VIDEO)
+        .unwrap_or(false);
+    if is_video {
+        MediaKind::Video
+    } else {
+        MediaKind::Image
+    }
 }
 
 /// A set of (potentially) duplicate images.
@@ -70,30 +176,267 @@ impl DupGroups {
     }
 }
 
+/// Backend-agnostic file metadata returned by [`StorageBackend::stat`].
+struct StorageMeta {
+    len: u64,
+    /// Modification time in seconds since the Unix epoch (0 if unavailable).
+    mtime: u64,
+}
+
+/// Source-of-truth for the duplicate files under review.
+///
+/// The handlers read, stat, trash and restore the files under review through
+/// this trait so the same dedup workflow can operate on a local directory, a
+/// NAS mount, or a remote host reachable over SFTP. The thumbnail cache and
+/// the trash manifest are always on the local filesystem.
+#[async_trait]
+trait StorageBackend: Send + Sync {
+    /// Open `path` for streaming reads.
+    async fn open_read(&self, path: &std::path::Path) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>>;
+
+    /// Open `path` for streaming reads starting at byte `offset`.
+    async fn open_read_from(&self, path: &std::path::Path, offset: u64) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>>;
+
+    /// Fetch size and modification time for `path`.
+    async fn stat(&self, path: &std::path::Path) -> std::io::Result<StorageMeta>;
+
+    /// Move `src` into the trash at `dst`, creating parent directories.
+    async fn move_to_trash(&self, src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()>;
+
+    /// Move a previously trashed file at `src` back to `dst`.
+    async fn restore(&self, src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()>;
+}
+
+/// [`StorageBackend`] backed by the local filesystem via `tokio::fs`.
+struct LocalFs;
+
+#[async_trait]
+impl StorageBackend for LocalFs {
+    async fn open_read(&self, path: &std::path::Path) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let file = tokio::fs::File::open(path).await?;
+        Ok(Box::new(file))
+    }
+
+    async fn open_read_from(&self, path: &std::path::Path, offset: u64) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        use tokio::io::AsyncSeekExt;
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        Ok(Box::new(file))
+    }
+
+    async fn stat(&self, path: &std::path::Path) -> std::io::Result<StorageMeta> {
+        let meta = tokio::fs::metadata(path).await?;
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(StorageMeta { len: meta.len(), mtime })
+    }
+
+    async fn move_to_trash(&self, src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+        if let Some(parent) = dst.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let (src, dst) = (src.to_path_buf(), dst.to_path_buf());
+        tokio::task::spawn_blocking(move || move_file(&src, &dst))
+            .await
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?
+    }
+
+    async fn restore(&self, src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+        if let Some(parent) = dst.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let (src, dst) = (src.to_path_buf(), dst.to_path_buf());
+        tokio::task::spawn_blocking(move || move_file(&src, &dst))
+            .await
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?
+    }
+}
+
+/// [`StorageBackend`] that reads duplicate sets from a remote host over SFTP.
+///
+/// `ssh2` is blocking, so each operation runs inside `spawn_blocking` on a
+/// freshly-authenticated session (agent auth). This keeps the type `Send` and
+/// avoids sharing a connection across tasks at the cost of a handshake per
+/// call — fine for the low request rate of a review UI.
+///
+/// Reads buffer the remainder of the object from the requested offset into
+/// memory before streaming; range requests seek server-side so only the tail
+/// is transferred, but callers should still expect one object's worth of RAM
+/// per in-flight request.
+struct SftpFs {
+    addr: String,
+    username: String,
+}
+
+impl SftpFs {
+    fn connect(addr: &str, username: &str) -> std::io::Result<ssh2::Session> {
+        let tcp = std::net::TcpStream::connect(addr)?;
+        let mut sess = ssh2::Session::new().map_err(to_io)?;
+        sess.set_tcp_stream(tcp);
+        sess.handshake().map_err(to_io)?;
+        sess.userauth_agent(username).map_err(to_io)?;
+        Ok(sess)
+    }
+}
+
+/// Map an `ssh2::Error` into an `io::Error` so it fits the trait signatures.
+fn to_io(err: ssh2::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, err)
+}
+
+#[async_trait]
+impl StorageBackend for SftpFs {
+    async fn open_read(&self, path: &std::path::Path) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let (addr, username, path) = (self.addr.clone(), self.username.clone(), path.to_path_buf());
+        let bytes = tokio::task::spawn_blocking(move || -> std::io::Result<Vec<u8>> {
+            let sess = SftpFs::connect(&addr, &username)?;
+            let sftp = sess.sftp().map_err(to_io)?;
+            let mut file = sftp.open(&path).map_err(to_io)?;
+            let mut buf = Vec::new();
+            std::io::Read::read_to_end(&mut file, &mut buf)?;
+            Ok(buf)
+        })
+        .await
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))??;
+        Ok(Box::new(std::io::Cursor::new(bytes)))
+    }
+
+    async fn open_read_from(&self, path: &std::path::Path, offset: u64) -> std::io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let (addr, username, path) = (self.addr.clone(), self.username.clone(), path.to_path_buf());
+        let bytes = tokio::task::spawn_blocking(move || -> std::io::Result<Vec<u8>> {
+            use std::io::{Read, Seek, SeekFrom};
+            let sess = SftpFs::connect(&addr, &username)?;
+            let sftp = sess.sftp().map_err(to_io)?;
+            let mut file = sftp.open(&path).map_err(to_io)?;
+            // Seek server-side so a range request only transfers the tail
+            // rather than re-fetching the whole object each seek.
+            file.seek(SeekFrom::Start(offset))?;
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            Ok(buf)
+        })
+        .await
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))??;
+        Ok(Box::new(std::io::Cursor::new(bytes)))
+    }
+
+    async fn stat(&self, path: &std::path::Path) -> std::io::Result<StorageMeta> {
+        let (addr, username, path) = (self.addr.clone(), self.username.clone(), path.to_path_buf());
+        tokio::task::spawn_blocking(move || -> std::io::Result<StorageMeta> {
+            let sess = SftpFs::connect(&addr, &username)?;
+            let sftp = sess.sftp().map_err(to_io)?;
+            let stat = sftp.stat(&path).map_err(to_io)?;
+            Ok(StorageMeta {
+                len: stat.size.unwrap_or(0),
+                mtime: stat.mtime.unwrap_or(0),
+            })
+        })
+        .await
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?
+    }
+
+    async fn move_to_trash(&self, src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+        self.remote_rename(src, dst).await
+    }
+
+    async fn restore(&self, src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+        self.remote_rename(src, dst).await
+    }
+}
+
+impl SftpFs {
+    /// Rename `src` to `dst` on the remote, creating `dst`'s parent directories first.
+    async fn remote_rename(&self, src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+        let (addr, username) = (self.addr.clone(), self.username.clone());
+        let (src, dst) = (src.to_path_buf(), dst.to_path_buf());
+        tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let sess = SftpFs::connect(&addr, &username)?;
+            let sftp = sess.sftp().map_err(to_io)?;
+            if let Some(parent) = dst.parent() {
+                // Best effort, outermost first: each level may already exist.
+                let mut dirs: Vec<&std::path::Path> = parent.ancestors().collect();
+                dirs.reverse();
+                for dir in dirs {
+                    let _ = sftp.mkdir(dir, 0o755);
+                }
+            }
+            sftp.rename(&src, &dst, None).map_err(to_io)
+        })
+        .await
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?
+    }
+}
+
+/// Live progress of an in-flight scan/parse job.
+#[derive(Clone, Default, Serialize)]
+struct ScanProgress {
+    files_seen: usize,
+    files_hashed: usize,
+    groups_found: usize,
+    done: bool,
+}
+
 /// Shared state for passing to route handlers.
+///
+/// `dups`/`progress` are populated incrementally by the background job, so
+/// handlers take a short read lock and clone out what they need rather than
+/// holding a reference across `.await` points.
 struct AppState {
-    dups: DupGroups,
+    dups: RwLock<DupGroups>,
+    progress: RwLock<ScanProgress>,
+    events: broadcast::Sender<String>,
+    storage: Box<dyn StorageBackend>,
     base_dir: std::path::PathBuf,
     trash_dir: std::path::PathBuf,
+    cache_dir: std::path::PathBuf,
 }
 
-fn parse_dups(filename: &str) -> Result<DupGroups> {
-    let line_re = Regex::new(r"^\s*\w+\((\d+)x(\d+)\): (.+)")?;
+impl AppState {
+    /// Apply `update` to the shared progress and broadcast the new snapshot.
+    fn bump_progress(&self, update: impl FnOnce(&mut ScanProgress)) {
+        let snapshot = {
+            let mut progress = self.progress.write().unwrap();
+            update(&mut progress);
+            progress.clone()
+        };
+        if let Ok(json) = serde_json::to_string(&snapshot) {
+            // A send error just means no SSE clients are connected.
+            let _ = self.events.send(json);
+        }
+    }
+}
 
-    // XXX guess initial size?
-    let mut dups = DupGroups::new(20);
+/// The work the background job should perform, derived from the CLI command.
+enum Job {
+    Parse(String),
+    Scan { dir: String, threshold: u32 },
+}
+
+/// Parse photodedupe output into `state`.
+///
+/// `files_seen`/`files_hashed` tick per line as the file is read, but groups
+/// are pushed only once the whole file has been read, sorted by each group's
+/// first path so the group sequence is stable across runs; until then no
+/// group is reviewable.
+fn parse_into(state: &Arc<AppState>, filename: &str) -> Result<()> {
+    let line_re = Regex::new(r"^\s*\w+\((\d+)x(\d+)\): (.+)")?;
 
     let reader = BufReader::new(fs::File::open(filename)?);
-    let mut group = Vec::new();
+    let mut groups: Vec<DupGroup> = Vec::new();
+    let mut group: DupGroup = Vec::new();
     // XXX line numbers in errors
     for line in reader.lines() {
         let line = line?;
+        state.bump_progress(|p| p.files_seen += 1);
         if !line.starts_with('\t') {
             // A line without a tab means a new group
             if !group.is_empty() {
-                dups.push_group(group);
+                groups.push(std::mem::take(&mut group));
             }
-            group = Vec::new();
         }
 
         let caps = line_re
@@ -111,19 +454,173 @@ fn parse_dups(filename: &str) -> Result<DupGroups> {
         };
 
         // XXX customize errors for failed int parsing
+        let path = path_cap.as_str().to_string();
         let info = ImgInfo {
-            path: path_cap.as_str().to_string(),
+            kind: media_kind(&path),
+            path,
             width: width_cap.as_str().parse()?,
             height: height_cap.as_str().parse()?,
         };
         group.push(info);
+        state.bump_progress(|p| p.files_hashed += 1);
     }
     if !group.is_empty() {
-        dups.push_group(group);
+        groups.push(group);
+    }
+
+    // Order groups by their first path, then publish each one.
+    groups.sort_unstable_by_key(|group| group[0].path.clone());
+    for group in groups {
+        state.dups.write().unwrap().push_group(group);
+        state.bump_progress(|p| p.groups_found += 1);
     }
 
-    dups.groups.sort_unstable_by_key(|group| group[0].path.clone());
-    Ok(dups)
+    Ok(())
+}
+
+/// Compute a 64-bit dHash fingerprint of an image along with its real
+/// dimensions.
+///
+/// The image is reduced to 9x8 grayscale and each of the 8 horizontal
+/// gradients per row contributes one bit: set when the left pixel is
+/// brighter than its right neighbour.
+fn dhash(path: &std::path::Path) -> Result<(u64, u32, u32)> {
+    // Videos are hashed via a decoded keyframe so a clip and its re-encode
+    // land in the same group as an equivalent still would.
+    let img = match media_kind(&path.to_string_lossy()) {
+        MediaKind::Video => {
+            let bytes = fs::read(path)?;
+            let frame = extract_video_frame(&bytes)?;
+            image::load_from_memory(&frame)?
+        }
+        MediaKind::Image => image::open(path)?,
+    };
+    let (width, height) = img.dimensions();
+
+    let small = img
+        .resize_exact(9, 8, image::imageops::FilterType::Lanczos3)
+        .to_luma8();
+
+    Ok((dhash_bits(&small), width, height))
+}
+
+/// Derive the 64-bit dHash fingerprint from a 9x8 grayscale image.
+///
+/// Bits are laid out row-major: bit `row * 8 + col` is set when the pixel at
+/// `(col, row)` is brighter than its right neighbour `(col + 1, row)`.
+fn dhash_bits(small: &image::GrayImage) -> u64 {
+    let mut hash = 0u64;
+    let mut bit = 0;
+    for row in 0..8u32 {
+        for col in 0..8u32 {
+            let left = small.get_pixel(col, row)[0];
+            let right = small.get_pixel(col + 1, row)[0];
+            if left > right {
+                hash |= 1 << bit;
+            }
+            bit += 1;
+        }
+    }
+    hash
+}
+
+/// Walk `dir` and group near-duplicate images using dHash fingerprints.
+///
+/// Two images belong to the same [`DupGroup`] when their fingerprints differ
+/// by at most `threshold` bits; the relation is closed transitively via
+/// union-find so a chain of similar images lands in one group. Each group is
+/// ordered largest-resolution-first so the best candidate is shown first.
+///
+/// Note: unlike parse mode, grouping is inherently global — a fingerprint can
+/// join any earlier one — so groups are only emitted once every file has been
+/// hashed. `files_seen`/`files_hashed` tick live during the walk, but
+/// `groups_found` (and thus reviewable groups) stays 0 until hashing finishes.
+fn scan_into(state: &Arc<AppState>, dir: &str, threshold: u32) -> Result<()> {
+    let mut infos: Vec<ImgInfo> = Vec::new();
+    let mut fingerprints: Vec<u64> = Vec::new();
+
+    let root = std::path::Path::new(dir);
+    // `trash_dir`/`cache_dir` live under the scan root; skip them so a second
+    // run doesn't re-ingest trashed originals or generated thumbnails as
+    // fresh duplicates.
+    let walk = WalkDir::new(dir).into_iter().filter_entry(|entry| {
+        let path = entry.path();
+        path != state.trash_dir && path != state.cache_dir
+    });
+    for entry in walk.filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        state.bump_progress(|p| p.files_seen += 1);
+        let path = entry.path();
+        match dhash(path) {
+            Ok((hash, width, height)) => {
+                let rel = path.strip_prefix(root).unwrap_or(path);
+                let rel = rel.to_string_lossy().into_owned();
+                infos.push(ImgInfo {
+                    kind: media_kind(&rel),
+                    path: rel,
+                    width,
+                    height,
+                });
+                fingerprints.push(hash);
+                state.bump_progress(|p| p.files_hashed += 1);
+            }
+            Err(err) => debug!("skipping {}: {}", path.display(), err),
+        }
+    }
+
+    // Hashing is done; grouping below is global so review becomes available
+    // only from here on.
+    info!("scan: hashed {} files, grouping", infos.len());
+
+    // Union every pair of images within the Hamming-distance threshold.
+    let mut uf = UnionFind::new(infos.len());
+    for i in 0..fingerprints.len() {
+        for j in (i + 1)..fingerprints.len() {
+            if (fingerprints[i] ^ fingerprints[j]).count_ones() <= threshold {
+                uf.union(i, j);
+            }
+        }
+    }
+
+    // Collect members by representative root.
+    use std::collections::HashMap;
+    let mut by_root: HashMap<usize, DupGroup> = HashMap::new();
+    for idx in 0..infos.len() {
+        let root = uf.find(idx);
+        by_root.entry(root).or_default().push(infos[idx].clone());
+    }
+
+    for mut group in by_root.into_values() {
+        // Only groups with an actual duplicate are worth reviewing.
+        if group.len() < 2 {
+            continue;
+        }
+        group.sort_unstable_by_key(|info| std::cmp::Reverse(info.width as u64 * info.height as u64));
+        state.dups.write().unwrap().push_group(group);
+        state.bump_progress(|p| p.groups_found += 1);
+    }
+
+    Ok(())
+}
+
+/// Run the scan/parse as a background task, marking progress `done` when it
+/// finishes (or fails).
+async fn run_job(state: Arc<AppState>, job: Job) {
+    let worker = Arc::clone(&state);
+    let result = tokio::task::spawn_blocking(move || match job {
+        Job::Parse(filename) => parse_into(&worker, &filename),
+        Job::Scan { dir, threshold } => scan_into(&worker, &dir, threshold),
+    })
+    .await;
+
+    match result {
+        Ok(Ok(())) => info!("scan job complete"),
+        Ok(Err(err)) => error!("scan job failed: {}", err),
+        Err(err) => error!("scan job panicked: {}", err),
+    }
+    state.bump_progress(|p| p.done = true);
 }
 
 // XXX gracefully handle errors in main
@@ -137,21 +634,46 @@ async fn main() {
 
     let args = Args::parse();
     // XXX make these optional
-    let base_dir = std::path::Path::new(&args.filename).parent().unwrap();
+    let (base_dir, job) = match args.command {
+        Command::Parse { filename } => {
+            let base_dir = std::path::Path::new(&filename).parent().unwrap().to_path_buf();
+            (base_dir, Job::Parse(filename))
+        }
+        Command::Scan { dir, threshold } => {
+            let base_dir = std::path::PathBuf::from(&dir);
+            (base_dir, Job::Scan { dir, threshold })
+        }
+    };
     let trash_dir = base_dir.join("trash");
+    let cache_dir = base_dir.join("cache");
 
     info!("base dir: {}", base_dir.to_string_lossy());
     info!("trash dir: {}", trash_dir.to_string_lossy());
+    info!("cache dir: {}", cache_dir.to_string_lossy());
 
-    let dups = parse_dups(&args.filename).unwrap();
-    // XXX bail if no dups
+    let storage: Box<dyn StorageBackend> = match args.sftp {
+        Some(addr) => {
+            info!("serving over SFTP from {} as {}", addr, args.user);
+            Box::new(SftpFs { addr, username: args.user })
+        }
+        None => Box::new(LocalFs),
+    };
 
+    let (events, _) = broadcast::channel(256);
     let state = Arc::new(AppState {
-        dups,
-        base_dir: base_dir.to_path_buf(),
+        dups: RwLock::new(DupGroups::new(20)),
+        progress: RwLock::new(ScanProgress::default()),
+        events,
+        storage,
+        base_dir,
         trash_dir,
+        cache_dir,
     });
 
+    // Kick off the scan in the background so the server can bind immediately
+    // and start serving groups as they are discovered.
+    tokio::spawn(run_job(Arc::clone(&state), job));
+
     // XXX log requests
     let app = Router::new()
         .route("/", get(|| async { Redirect::permanent("/group/0") }))
@@ -160,11 +682,23 @@ async fn main() {
             "/group/:group_idx/image/:image_idx",
             get(get_image).with_state(Arc::clone(&state)),
         )
+        .route(
+            "/group/:group_idx/image/:image_idx/thumb",
+            get(get_thumb).with_state(Arc::clone(&state)),
+        )
         .route(
             "/group/:group_idx/image/:image_idx",
             delete(trash_image).with_state(Arc::clone(&state)),
+        )
+        .route(
+            "/group/:group_idx/image/:image_idx/restore",
+            post(restore_image).with_state(Arc::clone(&state)),
+        )
+        .route("/trash", get(trash_page).with_state(Arc::clone(&state)))
+        .route("/status", get(status).with_state(Arc::clone(&state)))
+        .route("/events", get(events).with_state(Arc::clone(&state)))
         // static should be cached for a bit
-        ).nest_service( "/static", ServeDir::new("assets"));  // XXX package assets into binary
+        .nest_service( "/static", ServeDir::new("assets"));  // XXX package assets into binary
 
     // XXX port should be an arg
     axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
@@ -175,18 +709,48 @@ async fn main() {
 
 #[debug_handler]
 async fn group(Path(group_idx): Path<usize>, State(state): State<Arc<AppState>>) -> Response {
-    let Some(group) = state.dups.get_group(group_idx) else {
+    let dups = state.dups.read().unwrap();
+    let Some(group) = dups.get_group(group_idx) else {
+        drop(dups);
+        // No group yet: show the scan progress instead of bouncing to
+        // `/group/0`, which would loop forever while the job is still warming
+        // up and nothing has been discovered.
+        let progress = state.progress.read().unwrap().clone();
+        if progress.groups_found == 0 {
+            return HtmlTemplate(ScanningTemplate { progress }).into_response();
+        }
         return Redirect::to("/group/0").into_response();
     };
 
     let template = GroupTemplate {
         group_idx,
-        is_next_group: group_idx < state.dups.num_groups() - 1,
+        is_next_group: group_idx < dups.num_groups() - 1,
         group: group.to_vec(),  // XXX likely clone, avoid
     };
     HtmlTemplate(template).into_response()
 }
 
+#[derive(Template)]
+#[template(path = "scanning.html")]
+struct ScanningTemplate {
+    progress: ScanProgress,
+}
+
+#[debug_handler]
+async fn status(State(state): State<Arc<AppState>>) -> Json<ScanProgress> {
+    Json(state.progress.read().unwrap().clone())
+}
+
+#[debug_handler]
+async fn events(State(state): State<Arc<AppState>>) -> Sse<impl tokio_stream::Stream<Item = Result<Event, std::convert::Infallible>>> {
+    // Replay the current snapshot immediately, then stream live updates.
+    let current = serde_json::to_string(&*state.progress.read().unwrap()).unwrap_or_default();
+    let live = BroadcastStream::new(state.events.subscribe())
+        .filter_map(|msg| msg.ok().map(|data| Ok(Event::default().data(data))));
+    let stream = tokio_stream::once(Ok(Event::default().data(current))).chain(live);
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
 #[derive(Template)]
 #[template(path = "group.html")]
 struct GroupTemplate {
@@ -200,8 +764,9 @@ async fn get_image(
     Path((group_idx, image_idx)): Path<(usize, usize)>,
     State(state): State<Arc<AppState>>,
     TypedHeader(if_none_match): TypedHeader<IfNoneMatch>,
+    range: Option<TypedHeader<Range>>,
 ) -> Response {
-    let Some(image) = state.dups.get_image(group_idx, image_idx) else {
+    let Some(image) = state.dups.read().unwrap().get_image(group_idx, image_idx).cloned() else {
         return (StatusCode::NOT_FOUND, "Invalid group or image index".to_string()).into_response();
     };
 
@@ -227,13 +792,162 @@ async fn get_image(
 
     let source_path = state.base_dir.join(&image.path);
 
-    // `File` implements `AsyncRead`
-    let Ok(file) = tokio::fs::File::open(source_path).await else {
+    let Ok(stat) = state.storage.stat(&source_path).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to stat file").into_response();
+    };
+    let total = stat.len;
+
+    // Advertise range support so browsers can seek within video clips.
+    headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+
+    // Honour a (single) byte range if the client asked for one.
+    if let Some((start, end)) = range.and_then(|TypedHeader(r)| first_range(&r, total)) {
+        let Ok(reader) = state.storage.open_read_from(&source_path, start).await else {
+            return Redirect::to("/static/missing.png").into_response();
+        };
+        let len = end - start + 1;
+        let stream = ReaderStream::new(reader.take(len));
+        let body = StreamBody::new(stream);
+
+        headers.insert(header::CONTENT_LENGTH, len.to_string().parse().unwrap());
+        headers.insert(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, total).parse().unwrap(),
+        );
+        return (StatusCode::PARTIAL_CONTENT, headers, body).into_response();
+    }
+
+    let Ok(reader) = state.storage.open_read(&source_path).await else {
         return Redirect::to("/static/missing.png").into_response();
     };
 
+    let stream = ReaderStream::new(reader);
+    let body = StreamBody::new(stream);
+
+    headers.insert(header::CONTENT_LENGTH, total.to_string().parse().unwrap());
+    (headers, body).into_response()
+}
+
+/// Resolve the first satisfiable byte range against a `total`-byte resource,
+/// returning inclusive `(start, end)` offsets.
+fn first_range(range: &Range, total: u64) -> Option<(u64, u64)> {
+    use std::ops::Bound;
+    let (start_bound, end_bound) = range.satisfiable_ranges(total).next()?;
+    let start = match start_bound {
+        Bound::Included(v) => v,
+        Bound::Excluded(v) => v + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match end_bound {
+        Bound::Included(v) => v,
+        Bound::Excluded(v) => v.saturating_sub(1),
+        Bound::Unbounded => total.saturating_sub(1),
+    };
+    if total == 0 || start > end {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// Query parameters accepted by the thumbnail route.
+#[derive(Deserialize)]
+struct ThumbParams {
+    max_dim: Option<u32>,
+}
+
+/// Content hash identifying a thumbnail: `{path}:{mtime}:{max_dim}`.
+///
+/// The mtime keeps the key fresh when the source is replaced in place, and
+/// it is reused both as the on-disk cache filename and as the ETag value.
+fn thumb_hash(path: &str, mtime: u64, max_dim: u32) -> String {
+    sha256::digest(format!("{}:{}:{}", path, mtime, max_dim))
+}
+
+#[debug_handler]
+async fn get_thumb(
+    Path((group_idx, image_idx)): Path<(usize, usize)>,
+    Query(params): Query<ThumbParams>,
+    State(state): State<Arc<AppState>>,
+    TypedHeader(if_none_match): TypedHeader<IfNoneMatch>,
+) -> Response {
+    let Some(image) = state.dups.read().unwrap().get_image(group_idx, image_idx).cloned() else {
+        return (StatusCode::NOT_FOUND, "Invalid group or image index".to_string()).into_response();
+    };
+
+    let max_dim = params.max_dim.unwrap_or(DEFAULT_THUMB_MAX_DIM);
+    let source_path = state.base_dir.join(&image.path);
+
+    let Ok(source_meta) = state.storage.stat(&source_path).await else {
+        return Redirect::to("/static/missing.png").into_response();
+    };
+    let mtime = source_meta.mtime;
+
+    let hash = thumb_hash(&image.path, mtime, max_dim);
+    let etag_value = format!("\"{}\"", hash);
+    debug!("thumb etag: {}", etag_value);
+
+    let mut headers = header::HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, "image/jpeg".parse().unwrap());
+    headers.insert(header::ETAG, etag_value.parse().unwrap());
+
+    let Ok(etag) = etag_value.parse::<ETag>() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to parse etag: {}", etag_value)).into_response();
+    };
+    if !if_none_match.precondition_passes(&etag) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    // Generate the thumbnail unless it is already cached. The decode/resize/
+    // encode (and the video frame extraction) are CPU-bound and blocking, so
+    // they run on a blocking thread to avoid stalling the async runtime under
+    // the burst of concurrent `/thumb` requests the grid view fires.
+    let cache_path = state.cache_dir.join(format!("{}.jpg", hash));
+    let probe = cache_path.clone();
+    let cached = tokio::task::spawn_blocking(move || probe.exists())
+        .await
+        .unwrap_or(false);
+    if !cached {
+        // Pull the source bytes through the backend so remote images work too.
+        let mut bytes = Vec::new();
+        let read = match state.storage.open_read(&source_path).await {
+            Ok(mut reader) => reader.read_to_end(&mut bytes).await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = read {
+            error!("failed to read {}: {}", source_path.display(), err);
+            return Redirect::to("/static/missing.png").into_response();
+        }
+
+        let is_video = image.kind == MediaKind::Video;
+        let target = cache_path.clone();
+        let generated = tokio::task::spawn_blocking(move || -> Result<()> {
+            // For videos, decode a representative frame to feed the same cache.
+            let frame = if is_video {
+                extract_video_frame(&bytes)?
+            } else {
+                bytes
+            };
+            render_thumb(&frame, &target, max_dim)
+        })
+        .await;
+
+        match generated {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                error!("failed to render thumb for {}: {}", source_path.display(), err);
+                return Redirect::to("/static/missing.png").into_response();
+            }
+            Err(err) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, format!("thumbnail task failed: {}", err)).into_response();
+            }
+        }
+    }
+
+    let Ok(file) = tokio::fs::File::open(&cache_path).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to open thumbnail".to_string()).into_response();
+    };
     let Ok(stat) = file.metadata().await else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to stat file").into_response();
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to stat thumbnail").into_response();
     };
 
     let stream = ReaderStream::new(file);
@@ -243,12 +957,122 @@ async fn get_image(
     (headers, body).into_response()
 }
 
+/// Decode the encoded image in `source`, resize it to fit a `max_dim`x`max_dim`
+/// box preserving aspect ratio, and write the re-encoded JPEG to `cache_path`.
+fn render_thumb(source: &[u8], cache_path: &std::path::Path, max_dim: u32) -> Result<()> {
+    let img = image::load_from_memory(source)?;
+    let thumb = img.resize(max_dim, max_dim, image::imageops::FilterType::Lanczos3);
+
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    thumb.save_with_format(cache_path, image::ImageFormat::Jpeg)?;
+    Ok(())
+}
+
+/// Extract a single representative frame from an encoded video container,
+/// returning it as PNG bytes ready for [`render_thumb`].
+///
+/// `ffmpeg` needs a seekable input for most containers, so the bytes are
+/// staged to a temp file and the decoded frame is read back off its stdout.
+/// This is blocking (child process + temp-file IO) and is only ever called
+/// from a blocking context ([`get_thumb`]'s `spawn_blocking` and the scan
+/// job thread).
+fn extract_video_frame(bytes: &[u8]) -> Result<Vec<u8>> {
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // Per-call unique temp path so concurrent requests for the same clip don't
+    // share (and delete) each other's staging file.
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    let unique = format!("{}-{}", std::process::id(), SEQ.fetch_add(1, Ordering::Relaxed));
+    let mut tmp = std::env::temp_dir();
+    tmp.push(format!("dupgrind-{}.vid", unique));
+    fs::write(&tmp, bytes)?;
+
+    let output = Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-i"])
+        .arg(&tmp)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-vcodec", "png", "pipe:1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output();
+    let _ = fs::remove_file(&tmp);
+
+    let output = output?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(anyhow!("ffmpeg failed to extract a frame"));
+    }
+    Ok(output.stdout)
+}
+
+/// A single reversible trash operation, recorded in `trash_dir/manifest.jsonl`.
+#[derive(Clone, Serialize, Deserialize)]
+struct ManifestEntry {
+    group_idx: usize,
+    image_idx: usize,
+    original_path: String,
+    trash_path: String,
+    timestamp: u64,
+    sha256: String,
+}
+
+/// Seconds since the Unix epoch, or 0 if the clock is before it.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Move a file, falling back to copy-then-unlink across filesystems.
+///
+/// `fs::rename` cannot move between mounts and returns `EXDEV` there; in that
+/// case we stream the bytes to the destination and unlink the original.
+fn move_file(src: &std::path::Path, dst: &std::path::Path) -> std::io::Result<()> {
+    match fs::rename(src, dst) {
+        Ok(()) => Ok(()),
+        // `EXDEV`: source and destination live on different filesystems.
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            fs::copy(src, dst)?;
+            fs::remove_file(src)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Append one entry to the move manifest in a single write.
+fn append_manifest(trash_dir: &std::path::Path, entry: &ManifestEntry) -> Result<()> {
+    fs::create_dir_all(trash_dir)?;
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(trash_dir.join("manifest.jsonl"))?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Read every entry from the move manifest, ignoring malformed lines.
+fn read_manifest(trash_dir: &std::path::Path) -> Vec<ManifestEntry> {
+    let path = trash_dir.join("manifest.jsonl");
+    let Ok(file) = fs::File::open(path) else {
+        return Vec::new();
+    };
+    BufReader::new(file)
+        .lines()
+        .filter_map(|line| line.ok())
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
 #[debug_handler]
 async fn trash_image(
     Path((group_idx, image_idx)): Path<(usize, usize)>,
     State(state): State<Arc<AppState>>,
 ) -> (StatusCode, String) {
-    let Some(image) = state.dups.get_image(group_idx, image_idx) else {
+    let Some(image) = state.dups.read().unwrap().get_image(group_idx, image_idx).cloned() else {
         return (StatusCode::NOT_FOUND, "Invalid group or image index".to_string());
     };
 
@@ -257,28 +1081,91 @@ async fn trash_image(
 
     debug!("trashing {} to {}", source_path.display(), target_path.display());
 
-    let Some(target_parent) = target_path.parent() else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Target has no parent".to_string());
-    };
+    // Record the source digest in the manifest as an audit/integrity trail.
+    // Read through the backend so the digest works for remote sources too.
+    let mut sha256 = String::new();
+    if let Ok(mut reader) = state.storage.open_read(&source_path).await {
+        let mut bytes = Vec::new();
+        if reader.read_to_end(&mut bytes).await.is_ok() {
+            sha256 = sha256::digest(bytes.as_slice());
+        }
+    }
 
-    // Ensure that destination directory exists
-    match fs::create_dir_all(target_parent) {
-        Ok(_) => (),
-        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    if let Err(err) = state.storage.move_to_trash(&source_path, &target_path).await {
+        error!("failed to move {} to {}: {}", source_path.display(), target_path.display(), err);
+        return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
     }
 
-    // XXX This doesn't work for cross file system moves
-    match fs::rename(&source_path, &target_path) {
-        Ok(_) => (),
-        Err(err) => {
-            error!("failed to move {} to {}: {}", source_path.display(), target_path.display(), err);
-            return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
-        },
+    let entry = ManifestEntry {
+        group_idx,
+        image_idx,
+        original_path: source_path.to_string_lossy().into_owned(),
+        trash_path: target_path.to_string_lossy().into_owned(),
+        timestamp: unix_now(),
+        sha256,
+    };
+    if let Err(err) = append_manifest(&state.trash_dir, &entry) {
+        error!("failed to record manifest entry: {}", err);
+        return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
     }
 
     (StatusCode::OK, "Deleted".to_string())
 }
 
+#[debug_handler]
+async fn restore_image(
+    Path((group_idx, image_idx)): Path<(usize, usize)>,
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, String) {
+    // The last manifest entry for this image whose file is still in the trash.
+    // Existence is checked through the backend so remote trash paths work too.
+    let candidates = read_manifest(&state.trash_dir)
+        .into_iter()
+        .rev()
+        .filter(|e| e.group_idx == group_idx && e.image_idx == image_idx);
+
+    let mut entry = None;
+    for candidate in candidates {
+        if state.storage.stat(std::path::Path::new(&candidate.trash_path)).await.is_ok() {
+            entry = Some(candidate);
+            break;
+        }
+    }
+
+    let Some(entry) = entry else {
+        return (StatusCode::NOT_FOUND, "No restorable trash entry for that image".to_string());
+    };
+
+    let trash_path = std::path::PathBuf::from(&entry.trash_path);
+    let original_path = std::path::PathBuf::from(&entry.original_path);
+
+    if let Err(err) = state.storage.restore(&trash_path, &original_path).await {
+        error!("failed to restore {} to {}: {}", trash_path.display(), original_path.display(), err);
+        return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
+    }
+
+    (StatusCode::OK, "Restored".to_string())
+}
+
+#[derive(Template)]
+#[template(path = "trash.html")]
+struct TrashTemplate {
+    entries: Vec<ManifestEntry>,
+}
+
+#[debug_handler]
+async fn trash_page(State(state): State<Arc<AppState>>) -> Response {
+    // Only surface entries still sitting in the trash and thus restorable.
+    // Existence is checked through the backend so remote trash paths work too.
+    let mut entries = Vec::new();
+    for entry in read_manifest(&state.trash_dir) {
+        if state.storage.stat(std::path::Path::new(&entry.trash_path)).await.is_ok() {
+            entries.push(entry);
+        }
+    }
+    HtmlTemplate(TrashTemplate { entries }).into_response()
+}
+
 struct HtmlTemplate<T>(T);
 
 impl<T> IntoResponse for HtmlTemplate<T>
@@ -296,3 +1183,83 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    /// Build a 9x8 grayscale image whose columns increase left-to-right, so
+    /// every horizontal gradient has `left < right` (all fingerprint bits 0).
+    fn ascending_rows() -> GrayImage {
+        let mut img = GrayImage::new(9, 8);
+        for row in 0..8u32 {
+            for col in 0..9u32 {
+                img.put_pixel(col, row, Luma([(col * 20) as u8]));
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn dhash_bits_all_clear_and_all_set() {
+        let ascending = ascending_rows();
+        assert_eq!(dhash_bits(&ascending), 0);
+
+        let mut descending = GrayImage::new(9, 8);
+        for row in 0..8u32 {
+            for col in 0..9u32 {
+                descending.put_pixel(col, row, Luma([((8 - col) * 20) as u8]));
+            }
+        }
+        // 8 rows * 8 comparisons = all 64 bits set.
+        assert_eq!(dhash_bits(&descending), u64::MAX);
+    }
+
+    #[test]
+    fn dhash_bits_ordering_is_row_major() {
+        // Flip only the very first comparison (row 0, col 0) to left > right;
+        // it must land in the least-significant bit and nothing else.
+        let mut img = ascending_rows();
+        img.put_pixel(0, 0, Luma([255]));
+        assert_eq!(dhash_bits(&img), 1);
+    }
+
+    #[test]
+    fn union_find_merges_transitively() {
+        let mut uf = UnionFind::new(4);
+        uf.union(0, 1);
+        uf.union(2, 3);
+        assert_eq!(uf.find(0), uf.find(1));
+        assert_ne!(uf.find(0), uf.find(2));
+
+        uf.union(1, 2);
+        assert_eq!(uf.find(0), uf.find(3));
+    }
+
+    #[test]
+    fn thumb_hash_is_stable_and_key_sensitive() {
+        assert_eq!(thumb_hash("a.jpg", 1, 200), thumb_hash("a.jpg", 1, 200));
+        assert_ne!(thumb_hash("a.jpg", 1, 200), thumb_hash("a.jpg", 1, 400));
+        assert_ne!(thumb_hash("a.jpg", 1, 200), thumb_hash("a.jpg", 2, 200));
+        assert_ne!(thumb_hash("a.jpg", 1, 200), thumb_hash("b.jpg", 1, 200));
+    }
+
+    #[test]
+    fn first_range_resolves_inclusive_end() {
+        let range = Range::bytes(0..=9).unwrap();
+        assert_eq!(first_range(&range, 100), Some((0, 9)));
+    }
+
+    #[test]
+    fn first_range_open_ended_uses_last_byte() {
+        let range = Range::bytes(5..).unwrap();
+        assert_eq!(first_range(&range, 100), Some((5, 99)));
+    }
+
+    #[test]
+    fn first_range_empty_resource_is_none() {
+        let range = Range::bytes(0..=9).unwrap();
+        assert_eq!(first_range(&range, 0), None);
+    }
+}